@@ -0,0 +1,79 @@
+use std::io::{self, Read};
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, ValueEnum)]
+pub enum Algorithm {
+    /// CRC-32/IEEE, the same as zlib, gzip and `cksum -o 3`
+    // Reflected polynomial 0x04C11DB7 (0xEDB88320), initial value and final
+    // XOR 0xFFFFFFFF
+    Crc32,
+    Sha256,
+}
+
+pub enum Checksum {
+    Crc32(crc32fast::Hasher),
+    Sha256(Sha256),
+}
+
+impl Checksum {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Crc32 => Checksum::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Sha256 => Checksum::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Crc32(hasher) => hasher.update(data),
+            Checksum::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Hash everything read from the reader.
+    pub fn update_from(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.update(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Lowercase hex digest.
+    pub fn finish(self) -> String {
+        match self {
+            Checksum::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+            Checksum::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+        }
+    }
+}
+
+pub fn parse_digest_var(s: &str) -> Result<String, crate::ErrorBox> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Err("Digest must be a hex string")?;
+    }
+    Ok(s.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        let mut checksum = Checksum::new(Algorithm::Crc32);
+        checksum.update(b"123456789");
+        assert_eq!(checksum.finish(), "cbf43926");
+    }
+}
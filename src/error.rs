@@ -0,0 +1,82 @@
+use std::{error::Error, fmt};
+
+use crate::ErrorBox;
+
+/// Error with a message describing what was being done when the source failed.
+#[derive(Debug)]
+pub struct ContextError {
+    message: String,
+    source: ErrorBox,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ContextError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+pub trait Context<T> {
+    fn context<F, S>(self, message: F) -> Result<T, ErrorBox>
+    where
+        F: FnOnce() -> S,
+        S: Into<String>;
+}
+
+impl<T, E: Into<ErrorBox>> Context<T> for Result<T, E> {
+    fn context<F, S>(self, message: F) -> Result<T, ErrorBox>
+    where
+        F: FnOnce() -> S,
+        S: Into<String>,
+    {
+        self.map_err(|e| {
+            ErrorBox::from(ContextError {
+                message: message().into(),
+                source: e.into(),
+            })
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// Print the error to stderr:
+/// quiet only prints the top-level message,
+/// normal joins the whole source chain in one line,
+/// verbose prints each source on its own line.
+pub fn print_error(e: &(dyn Error + 'static), verbosity: Verbosity, color: bool) {
+    let mut message = if color {
+        format!("\x1b[1;31mError:\x1b[0m {e}")
+    } else {
+        format!("Error: {e}")
+    };
+    let sources = std::iter::successors(e.source(), |&e| e.source());
+    match verbosity {
+        Verbosity::Quiet => {}
+        Verbosity::Normal => {
+            for source in sources {
+                message.push_str(&format!(": {source}"));
+            }
+        }
+        Verbosity::Verbose => {
+            let mut sources = sources.peekable();
+            if sources.peek().is_some() {
+                message.push_str("\n\nCaused by:");
+            }
+            for (i, source) in sources.enumerate() {
+                message.push_str(&format!("\n    {i}: {source}"));
+            }
+        }
+    }
+    eprintln!("{message}");
+}
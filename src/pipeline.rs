@@ -0,0 +1,113 @@
+use std::{
+    fs::File,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::write_all_retry;
+
+// One being filled, one queued and one being written
+const BUFFERS: usize = 3;
+
+/// What reached the output.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Committed {
+    // Buffers written in full
+    pub buffers: u64,
+    pub bytes: u128,
+    pub calls: u64,
+}
+
+/// Writes on its own thread, so the next read overlaps the write of the last
+/// buffer. The buffers go back to the reader once written, so no more than
+/// a few are ever allocated.
+pub struct Pipeline {
+    chunks: SyncSender<Vec<u8>>,
+    free: Receiver<Vec<u8>>,
+    // Being filled by the reader, kept when it is not sent, like on a read error
+    current: Option<Vec<u8>>,
+    // Cleared to stop the writer without writing the queued buffers
+    running: Arc<AtomicBool>,
+    writer: JoinHandle<(File, Committed, io::Result<()>)>,
+}
+
+impl Pipeline {
+    pub fn new(mut output: File, buffer_size: usize, max_write: usize) -> Self {
+        let (chunks, queue) = mpsc::sync_channel::<Vec<u8>>(BUFFERS - 1);
+        let (recycle, free) = mpsc::sync_channel(BUFFERS);
+        for _ in 0..BUFFERS {
+            recycle.send(Vec::with_capacity(buffer_size)).unwrap();
+        }
+        let running = Arc::new(AtomicBool::new(true));
+        let writer_running = running.clone();
+        let writer = thread::spawn(move || {
+            let mut committed = Committed::default();
+            // Ends once the reader is done and the queue is drained
+            for chunk in queue {
+                if !writer_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                match write_all_retry(
+                    &mut output,
+                    &chunk,
+                    max_write,
+                    &writer_running,
+                    &mut committed.calls,
+                ) {
+                    Ok(n) => {
+                        committed.bytes += n as u128;
+                        if n < chunk.len() {
+                            break;
+                        }
+                        committed.buffers += 1;
+                    }
+                    Err(e) => return (output, committed, Err(e)),
+                }
+                // The reader may already be gone
+                let _ = recycle.send(chunk);
+            }
+            (output, committed, Ok(()))
+        });
+        Pipeline {
+            chunks,
+            free,
+            current: None,
+            running,
+            writer,
+        }
+    }
+
+    /// The buffer to read the next `len` bytes into, waiting for the writer to
+    /// free one. None if the writer stopped on an error, which `finish` returns.
+    pub fn buffer(&mut self, len: usize) -> Option<&mut [u8]> {
+        if self.current.is_none() {
+            self.current = Some(self.free.recv().ok()?);
+        }
+        let chunk = self.current.as_mut().unwrap();
+        chunk.resize(len, 0);
+        Some(chunk)
+    }
+
+    /// Queue the first `len` bytes of the buffer for the writer.
+    /// False if the writer stopped on an error.
+    pub fn send(&mut self, len: usize) -> bool {
+        let mut chunk = self.current.take().expect("No buffer to send");
+        chunk.truncate(len);
+        self.chunks.send(chunk).is_ok()
+    }
+
+    /// Wait for the writer to finish the queued buffers, or only the one
+    /// being written when interrupted, and take the output back.
+    pub fn finish(self, interrupted: bool) -> (File, Committed, io::Result<()>) {
+        if interrupted {
+            self.running.store(false, Ordering::SeqCst);
+        }
+        drop(self.chunks);
+        self.writer.join().expect("Writer thread panicked")
+    }
+}
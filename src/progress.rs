@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    time::Duration,
+};
+
+/// What is left of a transfer with a size or count limit.
+#[derive(Debug, Clone, Copy)]
+pub struct Remaining {
+    pub bytes: u128,
+    pub buffers: u64,
+}
+
+/// When the next progress record is written.
+pub enum Schedule {
+    Interval(Duration),
+    Buffers(u64),
+}
+
+/// Newline-delimited progress records for an external monitor, one per
+/// interval or number of buffers: `elapsed_ns bytes bytes_per_second`,
+/// followed by `remaining_bytes remaining_buffers` when the transfer is limited.
+pub struct ProgressPipe {
+    file: File,
+    schedule: Schedule,
+    // Elapsed time or buffer count of the next record
+    next_time: Duration,
+    next_count: u64,
+}
+
+impl ProgressPipe {
+    pub fn new(file: File, schedule: Schedule) -> Self {
+        let (next_time, next_count) = match schedule {
+            Schedule::Interval(interval) => (interval, u64::MAX),
+            Schedule::Buffers(n) => (Duration::MAX, n),
+        };
+        ProgressPipe {
+            file,
+            schedule,
+            next_time,
+            next_count,
+        }
+    }
+
+    pub fn due(&self, elapsed: Duration, count: u64) -> bool {
+        elapsed >= self.next_time || count >= self.next_count
+    }
+
+    pub fn report(
+        &mut self,
+        elapsed: Duration,
+        bytes: u128,
+        count: u64,
+        remaining: Option<Remaining>,
+    ) -> io::Result<()> {
+        let nanos = elapsed.as_nanos();
+        let speed = bytes * 1_000_000_000 / nanos.max(1);
+        let mut record = format!("{nanos} {bytes} {speed}");
+        if let Some(remaining) = remaining {
+            record.push_str(&format!(" {} {}", remaining.bytes, remaining.buffers));
+        }
+        record.push('\n');
+        // One write per record, so a reader never sees half of it
+        self.file.write_all(record.as_bytes())?;
+        // Skip the intervals missed by a slow read or write
+        match self.schedule {
+            Schedule::Interval(interval) => self.next_time = elapsed + interval,
+            Schedule::Buffers(n) => self.next_count = count + n,
+        }
+        Ok(())
+    }
+}
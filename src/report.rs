@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+/// Bumped when a field changes incompatibly, so consumers can branch on it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Why the transfer loop ended.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    #[default]
+    Eof,
+    CountReached,
+    SizeReached,
+    DurationReached,
+    Interrupted,
+}
+
+impl StopReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StopReason::Eof => "eof",
+            StopReason::CountReached => "count-reached",
+            StopReason::SizeReached => "size-reached",
+            StopReason::DurationReached => "duration-reached",
+            StopReason::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// Content generated into memory before the transfer.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Generation {
+    pub duration_ns: u128,
+    pub size: u128,
+}
+
+/// Machine-readable result of a transfer.
+#[derive(Debug, Default, Serialize, Deserialize)]
+// A report saved by an older version lacks the newer fields
+#[serde(default)]
+pub struct Report {
+    pub schema_version: u32,
+    pub label: Option<String>,
+    // RFC 3339 with milliseconds
+    pub start_time: String,
+    pub end_time: String,
+    pub duration_ns: u128,
+    pub buffer_size: u128,
+    pub count: u64,
+    pub passes: u64,
+    pub size: u128,
+    pub padded_size: u128,
+    pub bytes_per_second: u128,
+    pub read_calls: u64,
+    pub write_calls: u64,
+    pub seed: Option<u64>,
+    pub generation: Option<Generation>,
+    pub checksum: Option<String>,
+    pub output_checksum: Option<String>,
+    pub interrupted: bool,
+    pub stop_reason: StopReason,
+}
+
+/// Machine-readable result of `--compare`.
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub a: Report,
+    // None if interrupted before B
+    pub b: Option<Report>,
+    // Speed change of B from A
+    pub change_percent: Option<f64>,
+}